@@ -1,4 +1,6 @@
 use std::fs;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use clap::{Parser, Subcommand};
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
@@ -7,9 +9,14 @@ use rand::RngCore;
 use base64::{engine::general_purpose, Engine as _};
 
 use sha3::{Digest, Sha3_512};
-use aes_gcm::{Aes256Gcm, aead::{Aead, KeyInit, OsRng, generic_array::GenericArray}};
-use chacha20poly1305::{ChaCha20Poly1305, Key, XChaCha20Poly1305}; // (XChaCha unused, kept for future)
-use chacha20poly1305::aead::{Aead as CAead, KeyInit as CKeyInit};
+use aes_gcm::{Aes256Gcm, aead::{Aead, KeyInit, generic_array::GenericArray}};
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::{ChaCha20Poly1305, Key, XChaCha20Poly1305};
+use argon2::{Argon2, Algorithm, Version, Params};
+use x25519_dalek::{StaticSecret, PublicKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
 #[command(name="cipher", version)]
@@ -22,37 +29,102 @@ struct Cli {
 enum Cmd {
     /// encrypt
     Enc {
-        /// seed string (raw or hex: prefix with 0x)
+        /// seed string (raw or hex: prefix with 0x); mutually exclusive with --recipient
         #[arg(short='k', long="key")]
-        seed: String,
+        seed: Option<String>,
+        /// X25519 recipient public key (hex); mutually exclusive with --key
+        #[arg(long="recipient")]
+        recipient: Option<String>,
         /// input file
         #[arg(short='i', long="in")]
         infile: String,
         /// output file
         #[arg(short='o', long="out")]
         outfile: String,
+        /// custom pipeline JSON file (ordered step list); defaults to the built-in pipeline
+        #[arg(long="pipeline")]
+        pipeline: Option<String>,
     },
     /// decrypt
     Dec {
+        /// seed string (raw or hex: prefix with 0x); mutually exclusive with --identity
         #[arg(short='k', long="key")]
-        seed: String,
+        seed: Option<String>,
+        /// X25519 recipient private key (hex); mutually exclusive with --key
+        #[arg(long="identity")]
+        identity: Option<String>,
         #[arg(short='i', long="in")]
         infile: String,
         #[arg(short='o', long="out")]
         outfile: String,
+    },
+    /// validate and print the resolved pipeline definition as JSON, then exit
+    PrintPipeline {
+        /// custom pipeline JSON file; defaults to the built-in pipeline if omitted
+        #[arg(long="pipeline")]
+        pipeline: Option<String>,
     }
 }
 
+// A bundle file is a 4-byte big-endian header length, that many bytes of JSON
+// header, then the raw ciphertext bytes (dec's output is plain data, not a bundle).
+// Keeping the bulk payload out of the JSON means we never need to hold the
+// whole thing as one base64 string: `write_bundle_header`/`read_bundle_header`
+// below handle the framing, and for stream-only pipelines the data itself is
+// streamed straight through.
 #[derive(Serialize, Deserialize)]
-struct Bundle {
+struct BundleHeader {
     /// Random bundle identifier
     id: String,
+    /// Argon2id parameters, present only when `--key` was a passphrase (absent
+    /// for a raw 0x... key and in --recipient mode)
+    #[serde(skip_serializing_if="Option::is_none")]
+    kdf: Option<KdfInfo>,
+    /// Ephemeral X25519 public key (base64), present only in --recipient mode
+    #[serde(skip_serializing_if="Option::is_none")]
+    ephemeral_pub_b64: Option<String>,
+    /// The resolved, validated pipeline definition that produced `steps`, so
+    /// the bundle is self-describing without the generator that made it
+    pipeline: Vec<serde_json::Value>,
     /// Steps executed (serialized)
     steps: Vec<StepOut>,
-    /// Final ciphertext (base64) or plaintext for dec
-    data_b64: String,
 }
 
+fn write_bundle_header(writer: &mut impl Write, header: &BundleHeader) -> Result<()> {
+    let header_bytes = serde_json::to_vec(header)?;
+    writer.write_all(&(header_bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&header_bytes)?;
+    Ok(())
+}
+
+fn read_bundle_header(reader: &mut impl Read) -> Result<BundleHeader> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf)
+        .map_err(|e| anyhow::anyhow!("bad bundle: missing header length: {}", e))?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    let mut header_bytes = vec![0u8; len];
+    reader.read_exact(&mut header_bytes)
+        .map_err(|e| anyhow::anyhow!("bad bundle: truncated header: {}", e))?;
+    Ok(serde_json::from_slice(&header_bytes)?)
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfInfo {
+    /// Random salt used for this bundle (base64)
+    salt_b64: String,
+    /// Memory cost, in KiB
+    mem_kib: u32,
+    /// Time cost (iterations)
+    time_cost: u32,
+    /// Parallelism (lanes)
+    parallelism: u32,
+}
+
+// Argon2id defaults: 64 MiB / 3 iterations / 1 lane
+const ARGON2_MEM_KIB: u32 = 64 * 1024;
+const ARGON2_TIME_COST: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 struct StepOut {
     op: String,
@@ -62,179 +134,774 @@ struct StepOut {
     tag_b64: Option<String>,
     #[serde(skip_serializing_if="Option::is_none")]
     aad_b64: Option<String>,
+    /// STREAM chunking params, present for ops that encrypt chunk-by-chunk
+    #[serde(skip_serializing_if="Option::is_none")]
+    stream: Option<StreamInfo>,
 }
 
-fn parse_seed(seed: &str) -> Vec<u8> {
-    if seed.starts_with("0x") || seed.starts_with("0X") {
-        hex::decode(seed.trim_start_matches("0x").trim_start_matches("0X")).expect("bad hex seed")
+#[derive(Serialize, Deserialize)]
+struct StreamInfo {
+    /// Random 7-byte nonce prefix for this step (base64), shared by every chunk
+    nonce_prefix_b64: String,
+    /// Plaintext chunk size in bytes used when this step ran
+    chunk_size: u32,
+}
+
+// STREAM construction (à la age/libsodium secretstream): 64 KiB plaintext
+// chunks, each AEAD-sealed with nonce = 7-byte random prefix || 4-byte BE
+// chunk counter || 1-byte last-block flag. The flag binds the true end of
+// the stream into the AEAD tag so truncation/reordering is detected, and
+// chunking keeps any single AEAD invocation's working set bounded.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+fn stream_nonce(prefix: &[u8; 7], counter: u32, last: bool) -> [u8; 12] {
+    let mut n = [0u8; 12];
+    n[..7].copy_from_slice(prefix);
+    n[7..11].copy_from_slice(&counter.to_be_bytes());
+    n[11] = last as u8;
+    n
+}
+
+fn frame_encrypt_stream(data: &[u8], prefix: [u8; 7], mut enc_chunk: impl FnMut(&[u8], [u8; 12]) -> Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16 * (data.len() / STREAM_CHUNK_SIZE + 1));
+    let total = data.len();
+    let mut offset = 0usize;
+    let mut counter: u32 = 0;
+    loop {
+        let end = (offset + STREAM_CHUNK_SIZE).min(total);
+        let last = end == total;
+        let ct = enc_chunk(&data[offset..end], stream_nonce(&prefix, counter, last));
+        out.extend_from_slice(&(ct.len() as u32).to_be_bytes());
+        out.extend_from_slice(&ct);
+        offset = end;
+        counter += 1;
+        if last { break; }
+    }
+    out
+}
+
+fn frame_decrypt_stream(framed: &[u8], prefix: [u8; 7], mut dec_chunk: impl FnMut(&[u8], [u8; 12]) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(framed.len());
+    let mut pos = 0usize;
+    let mut counter: u32 = 0;
+    loop {
+        if pos + 4 > framed.len() {
+            anyhow::bail!("truncated stream frame: missing chunk length at offset {}", pos);
+        }
+        let len = u32::from_be_bytes(framed[pos..pos + 4].try_into().unwrap()) as usize;
+        pos += 4;
+        if pos + len > framed.len() {
+            anyhow::bail!("truncated stream frame: chunk at offset {} declares {} bytes but only {} remain", pos, len, framed.len() - pos);
+        }
+        let ct = &framed[pos..pos + len];
+        pos += len;
+        let last = pos == framed.len();
+        out.extend_from_slice(&dec_chunk(ct, stream_nonce(&prefix, counter, last))?);
+        counter += 1;
+        if last { break; }
+    }
+    Ok(out)
+}
+
+/// Reads up to `buf.len()` bytes from `r`, stopping early only at EOF (a
+/// short final read), so callers can tell a genuine last chunk from a
+/// still-in-progress one without needing the total input length up front.
+fn read_up_to(r: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+type ChunkEncryptor<'a> = Box<dyn FnMut(&[u8], [u8; 12]) -> Vec<u8> + 'a>;
+type ChunkDecryptor<'a> = Box<dyn FnMut(&[u8], [u8; 12]) -> Result<Vec<u8>> + 'a>;
+
+/// One stage of a fused multi-op STREAM pipeline: a 7-byte nonce prefix plus
+/// the per-chunk AEAD closure for that stage's cipher+key+aad.
+struct EncStage<'a> {
+    prefix: [u8; 7],
+    encrypt_chunk: ChunkEncryptor<'a>,
+}
+
+struct DecStage<'a> {
+    prefix: [u8; 7],
+    decrypt_chunk: ChunkDecryptor<'a>,
+}
+
+/// Streams `reader` -> `writer` through every `stages` entry in order,
+/// fusing them into a single chunked pass: each STREAM_CHUNK_SIZE-sized
+/// plaintext chunk is AEAD-sealed by stage 0, the result framed and sealed
+/// by stage 1, and so on, before the fully-nested chunk is written out.
+/// Memory use stays bounded by a couple of chunks regardless of input size,
+/// which is the whole point of STREAM chunking for multi-gigabyte inputs.
+fn stream_pipeline_encrypt(mut reader: impl Read, mut writer: impl Write, stages: &mut [EncStage]) -> Result<()> {
+    let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+    let mut lookahead: Option<u8> = None;
+    let mut counters = vec![0u32; stages.len()];
+    loop {
+        let mut filled = 0;
+        if let Some(b) = lookahead.take() {
+            buf[0] = b;
+            filled = 1;
+        }
+        filled += read_up_to(&mut reader, &mut buf[filled..])?;
+
+        let last = if filled < STREAM_CHUNK_SIZE {
+            true
+        } else {
+            let mut peek = [0u8; 1];
+            match reader.read(&mut peek)? {
+                0 => true,
+                1 => { lookahead = Some(peek[0]); false }
+                _ => unreachable!(),
+            }
+        };
+
+        let mut chunk = buf[..filled].to_vec();
+        for (stage, counter) in stages.iter_mut().zip(counters.iter_mut()) {
+            let nonce = stream_nonce(&stage.prefix, *counter, last);
+            let ct = (stage.encrypt_chunk)(&chunk, nonce);
+            *counter += 1;
+            let mut framed = Vec::with_capacity(4 + ct.len());
+            framed.extend_from_slice(&(ct.len() as u32).to_be_bytes());
+            framed.extend_from_slice(&ct);
+            chunk = framed;
+        }
+        writer.write_all(&chunk)?;
+        if last { break; }
+    }
+    Ok(())
+}
+
+/// Inverse of `stream_pipeline_encrypt`: unwraps the nested per-chunk frames
+/// from the outermost stage (the last one applied at encrypt time) down to
+/// the innermost, writing the recovered plaintext chunk straight to `writer`.
+fn stream_pipeline_decrypt(mut reader: impl Read, mut writer: impl Write, stages: &mut [DecStage]) -> Result<()> {
+    if stages.is_empty() {
+        // with no stages the encrypt side wrote the input through unframed
+        io::copy(&mut reader, &mut writer)?;
+        return Ok(());
+    }
+    let mut counters = vec![0u32; stages.len()];
+    let mut lookahead: Option<u8> = None;
+    loop {
+        let mut len_buf = [0u8; 4];
+        let mut filled = 0;
+        if let Some(b) = lookahead.take() {
+            len_buf[0] = b;
+            filled = 1;
+        }
+        filled += read_up_to(&mut reader, &mut len_buf[filled..])?;
+        if filled == 0 {
+            anyhow::bail!("truncated stream frame: missing final chunk");
+        }
+        anyhow::ensure!(filled == 4, "truncated stream frame: incomplete chunk length prefix");
+        let len = u32::from_be_bytes(len_buf) as usize;
+        let mut outer = vec![0u8; len];
+        let got = read_up_to(&mut reader, &mut outer)?;
+        anyhow::ensure!(got == len, "truncated stream frame: chunk declared {} bytes but only {} were available", len, got);
+
+        let mut peek = [0u8; 1];
+        let last = match reader.read(&mut peek)? {
+            0 => true,
+            1 => { lookahead = Some(peek[0]); false }
+            _ => unreachable!(),
+        };
+
+        let last_idx = stages.len() - 1;
+        let mut data = outer;
+        for (i, stage) in stages.iter_mut().enumerate().rev() {
+            let ct = if i == last_idx {
+                data
+            } else {
+                anyhow::ensure!(data.len() >= 4, "truncated nested stream frame");
+                let inner_len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                anyhow::ensure!(data.len() == 4 + inner_len, "nested stream frame length mismatch");
+                data[4..].to_vec()
+            };
+            let nonce = stream_nonce(&stage.prefix, counters[i], last);
+            data = (stage.decrypt_chunk)(&ct, nonce)?;
+            counters[i] += 1;
+        }
+        writer.write_all(&data)?;
+        if last { break; }
+    }
+    Ok(())
+}
+
+fn parse_seed(seed: &str) -> Result<Zeroizing<Vec<u8>>> {
+    Ok(Zeroizing::new(if seed.starts_with("0x") || seed.starts_with("0X") {
+        hex::decode(seed.trim_start_matches("0x").trim_start_matches("0X"))
+            .map_err(|e| anyhow::anyhow!("bad hex seed: {}", e))?
     } else {
         seed.as_bytes().to_vec()
+    }))
+}
+
+/// Root key for a fresh encryption: either a raw 256-bit key (no KDF) or a
+/// freshly Argon2id-stretched passphrase key (with the params to reproduce it).
+enum RootKey {
+    Raw(Zeroizing<[u8; 32]>),
+    Derived { key: Zeroizing<[u8; 32]>, kdf: KdfInfo },
+}
+
+fn argon2_derive(seedb: &[u8], salt: &[u8], mem_kib: u32, time_cost: u32, parallelism: u32) -> Result<Zeroizing<[u8; 32]>> {
+    let params = Params::new(mem_kib, time_cost, parallelism, Some(32))
+        .map_err(|e| anyhow::anyhow!("bad argon2 params: {}", e))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2.hash_password_into(seedb, salt, &mut key[..])
+        .map_err(|e| anyhow::anyhow!("argon2 kdf failed: {}", e))?;
+    Ok(key)
+}
+
+// `--key 0x...` is treated as an explicit raw 256-bit key with no KDF stage;
+// anything else is a passphrase that gets stretched via Argon2id.
+fn derive_key_enc(seed: &str) -> Result<RootKey> {
+    if seed.starts_with("0x") || seed.starts_with("0X") {
+        let raw = parse_seed(seed)?;
+        anyhow::ensure!(raw.len() == 32, "raw key mode (0x...) requires exactly 32 bytes (64 hex chars)");
+        let mut k = Zeroizing::new([0u8; 32]);
+        k.copy_from_slice(&raw);
+        Ok(RootKey::Raw(k))
+    } else {
+        let seedb = parse_seed(seed)?;
+        let mut salt = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        let key = argon2_derive(&seedb, &salt, ARGON2_MEM_KIB, ARGON2_TIME_COST, ARGON2_PARALLELISM)?;
+        Ok(RootKey::Derived {
+            key,
+            kdf: KdfInfo {
+                salt_b64: general_purpose::STANDARD.encode(salt),
+                mem_kib: ARGON2_MEM_KIB,
+                time_cost: ARGON2_TIME_COST,
+                parallelism: ARGON2_PARALLELISM,
+            },
+        })
+    }
+}
+
+fn derive_key_dec(seed: &str, kdf: &Option<KdfInfo>) -> Result<Zeroizing<[u8; 32]>> {
+    match kdf {
+        None => {
+            let raw = parse_seed(seed)?;
+            anyhow::ensure!(raw.len() == 32, "raw key mode (0x...) requires exactly 32 bytes (64 hex chars)");
+            let mut k = Zeroizing::new([0u8; 32]);
+            k.copy_from_slice(&raw);
+            Ok(k)
+        }
+        Some(info) => {
+            let seedb = parse_seed(seed)?;
+            let salt = general_purpose::STANDARD.decode(&info.salt_b64)
+                .map_err(|e| anyhow::anyhow!("bad salt: {}", e))?;
+            argon2_derive(&seedb, &salt, info.mem_kib, info.time_cost, info.parallelism)
+        }
     }
 }
 
-fn rolling_key(cur: &[u8], counter: u64, extra: &[u8]) -> [u8; 32] {
+fn hex32(s: &str, what: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(s.trim_start_matches("0x").trim_start_matches("0X"))
+        .map_err(|e| anyhow::anyhow!("bad hex in {}: {}", what, e))?;
+    anyhow::ensure!(bytes.len() == 32, "{} must be 32 bytes (64 hex chars)", what);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+// Sealed-box-style X25519+HKDF: an ephemeral keypair is generated per message,
+// ECDH'd against the recipient's public key, and the shared secret is run
+// through HKDF-SHA256 (ephemeral pubkey as salt/info) to get the root key.
+fn recipient_derive_key(recipient_hex: &str) -> Result<(Zeroizing<[u8; 32]>, [u8; 32])> {
+    let recipient_pub = PublicKey::from(hex32(recipient_hex, "recipient pubkey")?);
+
+    let eph_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let eph_pub = PublicKey::from(&eph_secret);
+    let shared = eph_secret.diffie_hellman(&recipient_pub);
+
+    let hk = Hkdf::<Sha256>::new(Some(eph_pub.as_bytes()), shared.as_bytes());
+    let mut key = Zeroizing::new([0u8; 32]);
+    hk.expand(eph_pub.as_bytes(), &mut key[..])
+        .map_err(|e| anyhow::anyhow!("hkdf expand failed: {}", e))?;
+    Ok((key, *eph_pub.as_bytes()))
+}
+
+fn identity_derive_key(identity_hex: &str, eph_pub_b64: &str) -> Result<Zeroizing<[u8; 32]>> {
+    let raw_identity = Zeroizing::new(hex32(identity_hex, "identity key")?);
+    let secret = StaticSecret::from(*raw_identity);
+
+    let eph_pub_bytes = general_purpose::STANDARD.decode(eph_pub_b64)
+        .map_err(|e| anyhow::anyhow!("bad ephemeral pubkey: {}", e))?;
+    anyhow::ensure!(eph_pub_bytes.len() == 32, "ephemeral pubkey must be 32 bytes");
+    let mut epb = [0u8; 32];
+    epb.copy_from_slice(&eph_pub_bytes);
+    let eph_pub = PublicKey::from(epb);
+
+    let shared = secret.diffie_hellman(&eph_pub);
+    let hk = Hkdf::<Sha256>::new(Some(eph_pub.as_bytes()), shared.as_bytes());
+    let mut key = Zeroizing::new([0u8; 32]);
+    hk.expand(eph_pub.as_bytes(), &mut key[..])
+        .map_err(|e| anyhow::anyhow!("hkdf expand failed: {}", e))?;
+    Ok(key)
+}
+
+fn rolling_key(cur: &[u8], counter: u64, extra: &[u8]) -> Zeroizing<[u8; 32]> {
     let mut h = Sha3_512::new();
     h.update(cur);
     h.update(counter.to_be_bytes());
     h.update(extra);
-    let out = h.finalize();
-    let mut key = [0u8; 32];
+    let mut out = h.finalize();
+    let mut key = Zeroizing::new([0u8; 32]);
     key.copy_from_slice(&out[..32]);
+    // `out` holds the new key in its first 32 bytes too; wipe it rather than
+    // letting it linger on the stack until the next call reuses the space.
+    out.as_mut_slice().iter_mut().for_each(|b| *b = 0);
     key
 }
 
-fn aesgcm_encrypt(k: &[u8;32], pt: &[u8], aad: &[u8]) -> (Vec<u8>, [u8;12], [u8;16]) {
+fn aesgcm_stream_encrypt(k: &[u8;32], data: &[u8], aad: &[u8], prefix: [u8;7]) -> Vec<u8> {
     let key = GenericArray::from_slice(k);
     let cipher = Aes256Gcm::new(key);
+    frame_encrypt_stream(data, prefix, |chunk, nonce| {
+        cipher.encrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: chunk, aad })
+            .expect("AES-GCM stream enc")
+    })
+}
+
+fn aesgcm_stream_decrypt(k: &[u8;32], framed: &[u8], aad: &[u8], prefix: [u8;7]) -> Result<Vec<u8>> {
+    let key = GenericArray::from_slice(k);
+    let cipher = Aes256Gcm::new(key);
+    frame_decrypt_stream(framed, prefix, |ct, nonce| {
+        cipher.decrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: ct, aad })
+            .map_err(|e| anyhow::anyhow!("AES-GCM stream dec failed: {}", e))
+    })
+}
+
+// AES-256-GCM-SIV: nonce-misuse-resistant, so an accidental nonce repeat only
+// leaks whether two plaintexts were equal rather than the authentication key.
+fn aesgcmsiv_encrypt(k: &[u8;32], pt: &[u8], aad: &[u8]) -> (Vec<u8>, [u8;12]) {
+    let key = GenericArray::from_slice(k);
+    let cipher = Aes256GcmSiv::new(key);
     let mut nonce = [0u8;12];
     rand::rngs::OsRng.fill_bytes(&mut nonce);
     let nonce_ga = GenericArray::from_slice(&nonce);
-    let ct = cipher.encrypt(nonce_ga, aes_gcm::aead::Payload { msg: pt, aad }).expect("AES-GCM enc");
-    // AES-GCM in this crate appends tag at end; but we want separate tag -> split
-    // For aes-gcm crate, tag is not returned separately. We'll serialize alongside ciphertext by not splitting.
-    // Workaround: we’ll just use the whole ct as-is and rely on decrypt to verify, tag is internal.
-    // To expose tag separately we'd need aes-gcm's streaming interface; we'll keep ct only.
-    // To remain symmetric, we'll not output tag here.
-    let tag = [0u8;16]; // placeholder not used; kept for schema parity
-    (ct, nonce, tag)
+    let ct = cipher.encrypt(nonce_ga, aes_gcm::aead::Payload { msg: pt, aad }).expect("AES-GCM-SIV enc");
+    (ct, nonce)
 }
 
-fn aesgcm_decrypt(k: &[u8;32], nonce:&[u8;12], ct:&[u8], aad:&[u8]) -> Vec<u8> {
+fn aesgcmsiv_decrypt(k: &[u8;32], nonce:&[u8;12], ct:&[u8], aad:&[u8]) -> Result<Vec<u8>> {
     let key = GenericArray::from_slice(k);
-    let cipher = Aes256Gcm::new(key);
+    let cipher = Aes256GcmSiv::new(key);
     let nonce_ga = GenericArray::from_slice(nonce);
-    cipher.decrypt(nonce_ga, aes_gcm::aead::Payload { msg: ct, aad }).expect("AES-GCM dec")
+    cipher.decrypt(nonce_ga, aes_gcm::aead::Payload { msg: ct, aad })
+        .map_err(|e| anyhow::anyhow!("AES-GCM-SIV dec failed: {}", e))
 }
 
-fn chacha_encrypt(k:&[u8;32], pt:&[u8], aad:&[u8]) -> (Vec<u8>, [u8;12], [u8;16]) {
+fn chacha_stream_encrypt(k: &[u8;32], data: &[u8], aad: &[u8], prefix: [u8;7]) -> Vec<u8> {
     let key = Key::from_slice(k);
     let cipher = ChaCha20Poly1305::new(key);
-    let mut nonce = [0u8;12];
+    frame_encrypt_stream(data, prefix, |chunk, nonce| {
+        cipher.encrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: chunk, aad })
+            .expect("chacha stream enc")
+    })
+}
+
+fn chacha_stream_decrypt(k: &[u8;32], framed: &[u8], aad: &[u8], prefix: [u8;7]) -> Result<Vec<u8>> {
+    let key = Key::from_slice(k);
+    let cipher = ChaCha20Poly1305::new(key);
+    frame_decrypt_stream(framed, prefix, |ct, nonce| {
+        cipher.decrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: ct, aad })
+            .map_err(|e| anyhow::anyhow!("chacha stream dec failed: {}", e))
+    })
+}
+
+// XChaCha20Poly1305 takes a 192-bit (24-byte) nonce, large enough that random
+// generation carries negligible collision risk even across huge message counts,
+// unlike the 96-bit nonces used by chacha20poly1305_enc/aesgcm_enc.
+fn xchacha_encrypt(k: &[u8;32], pt: &[u8], aad: &[u8]) -> (Vec<u8>, [u8;24]) {
+    let key = Key::from_slice(k);
+    let cipher = XChaCha20Poly1305::new(key);
+    let mut nonce = [0u8;24];
     rand::rngs::OsRng.fill_bytes(&mut nonce);
     let ct = cipher.encrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: pt, aad })
-        .expect("chacha enc");
-    // chacha20poly1305 crate also keeps tag internally; same symmetry as above.
-    let tag = [0u8;16];
-    (ct, nonce, tag)
+        .expect("xchacha enc");
+    (ct, nonce)
 }
 
-fn chacha_decrypt(k:&[u8;32], nonce:&[u8;12], ct:&[u8], aad:&[u8]) -> Vec<u8> {
+fn xchacha_decrypt(k: &[u8;32], nonce: &[u8;24], ct: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
     let key = Key::from_slice(k);
-    let cipher = ChaCha20Poly1305::new(key);
-    cipher.decrypt(&nonce.clone().into(), chacha20poly1305::aead::Payload { msg: ct, aad })
-        .expect("chacha dec")
+    let cipher = XChaCha20Poly1305::new(key);
+    cipher.decrypt(&(*nonce).into(), chacha20poly1305::aead::Payload { msg: ct, aad })
+        .map_err(|e| anyhow::anyhow!("xchacha dec failed: {}", e))
+}
+
+// Ops a pipeline step is allowed to name; anything else is rejected with a
+// clear error instead of panicking deep inside the enc/dec match arms.
+const KNOWN_OPS: &[&str] = &[
+    "rolling_key",
+    "aesgcm_enc",
+    "chacha20poly1305_enc",
+    "aesgcmsiv_enc",
+    "xchacha20poly1305_enc",
+];
+
+fn load_pipeline(path: Option<&str>) -> Result<Vec<serde_json::Value>> {
+    let raw = match path {
+        Some(p) => fs::read_to_string(p)?,
+        None => PIPELINE_JSON.to_string(),
+    };
+    let steps: Vec<serde_json::Value> = serde_json::from_str(&raw)?;
+    for s in &steps {
+        let op = s.get("op").and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("pipeline step missing string \"op\" field: {}", s))?;
+        if !KNOWN_OPS.contains(&op) {
+            anyhow::bail!("unknown pipeline op \"{}\"; known ops: {}", op, KNOWN_OPS.join(", "));
+        }
+    }
+    Ok(steps)
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    // pipeline baked in at generation time:
-    // {[{"op":"rolling_key","extra":"time_ns"},{"op":"aesgcm_enc","aad":"adap"},{"op":"chacha20poly1305_enc","aad":"evolve"}]}
-
-    let steps: Vec<serde_json::Value> = serde_json::from_str(PIPELINE_JSON)?;
     match cli.cmd {
-        Cmd::Enc { seed, infile, outfile } => {
-            let seedb = parse_seed(&seed);
-            let mut key = {
-                let mut k = [0u8;32];
-                if seedb.len() >= 32 { k.copy_from_slice(&seedb[..32]); }
-                else {
-                    k[..seedb.len()].copy_from_slice(&seedb);
-                    for i in seedb.len()..32 { k[i] = 0; }
+        Cmd::PrintPipeline { pipeline } => {
+            let steps = load_pipeline(pipeline.as_deref())?;
+            println!("{}", serde_json::to_string_pretty(&steps)?);
+        }
+        Cmd::Enc { seed, recipient, infile, outfile, pipeline } => {
+            let steps = load_pipeline(pipeline.as_deref())?;
+            let (mut key, kdf, ephemeral_pub_b64) = match (seed, recipient) {
+                (Some(seed), None) => match derive_key_enc(&seed)? {
+                    RootKey::Raw(k) => (k, None, None),
+                    RootKey::Derived { key, kdf } => (key, Some(kdf), None),
+                },
+                (None, Some(recipient)) => {
+                    let (key, eph_pub) = recipient_derive_key(&recipient)?;
+                    (key, None, Some(general_purpose::STANDARD.encode(eph_pub)))
                 }
-                k
+                _ => anyhow::bail!("enc: specify exactly one of --key or --recipient"),
             };
-            let mut data = fs::read(infile)?;
+
+            // The key schedule only ever moves forward (each rolling_key ratchets
+            // off the previous key), so precompute the key in effect at every
+            // pipeline entry before touching any file data — mirrors exactly how
+            // `Dec` below replays the same schedule forward on decrypt.
             let mut counter: u64 = 1;
+            let mut step_keys: Vec<Zeroizing<[u8; 32]>> = Vec::with_capacity(steps.len());
+            for s in steps.iter() {
+                let op = s.get("op").and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("pipeline entry missing string \"op\" field: {}", s))?;
+                if op == "rolling_key" {
+                    let extra = counter.to_be_bytes();
+                    key = rolling_key(&key[..], counter, &extra);
+                    counter += 1;
+                }
+                step_keys.push(key.clone());
+            }
 
-            let mut out_steps: Vec<StepOut> = Vec::new();
+            // Pipelines built entirely from STREAM-chunked ops (aesgcm_enc /
+            // chacha20poly1305_enc, modulo rolling_key which only ratchets the
+            // key and never touches data) stream straight from infile to outfile
+            // in STREAM_CHUNK_SIZE pieces, so memory use stays bounded regardless
+            // of input size. aesgcmsiv_enc / xchacha20poly1305_enc are inherently
+            // whole-message (a single nonce covers the entire ciphertext), so a
+            // pipeline that mixes one in falls back to buffering the file.
+            let stream_only = steps.iter().all(|s| {
+                matches!(
+                    s.get("op").and_then(|v| v.as_str()),
+                    Some("rolling_key") | Some("aesgcm_enc") | Some("chacha20poly1305_enc")
+                )
+            });
 
-            for s in steps.iter() {
-                let op = s.get("op").unwrap().as_str().unwrap();
-                match op {
-                    "rolling_key" => {
-                        let extra = counter.to_be_bytes();
-                        key = rolling_key(&key, counter, &extra);
-                        counter += 1;
-                        out_steps.push(StepOut { op: op.to_string(), nonce_b64: None, tag_b64: None, aad_b64: None });
-                    }
-                    "chacha20poly1305_enc" => {
-                        let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
-                        let (ct, nonce, _tag) = chacha_encrypt(&key, &data, &aad);
-                        data = ct;
-                        out_steps.push(StepOut {
-                            op: op.to_string(),
-                            nonce_b64: Some(general_purpose::STANDARD.encode(nonce)),
-                            tag_b64: None,
-                            aad_b64: Some(general_purpose::STANDARD.encode(aad)),
-                        });
-                    }
-                    "aesgcm_enc" => {
-                        let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
-                        let (ct, nonce, _tag) = aesgcm_encrypt(&key, &data, &aad);
-                        data = ct;
-                        out_steps.push(StepOut {
-                            op: op.to_string(),
-                            nonce_b64: Some(general_purpose::STANDARD.encode(nonce)),
-                            tag_b64: None,
-                            aad_b64: Some(general_purpose::STANDARD.encode(aad)),
-                        });
+            let mut out_steps: Vec<StepOut> = Vec::with_capacity(steps.len());
+
+            if stream_only {
+                let mut enc_stages: Vec<EncStage> = Vec::new();
+                for (s, step_key) in steps.iter().zip(step_keys.iter()) {
+                    let op = s.get("op").and_then(|v| v.as_str()).unwrap();
+                    match op {
+                        "rolling_key" => {
+                            out_steps.push(StepOut { op: op.to_string(), nonce_b64: None, tag_b64: None, aad_b64: None, stream: None });
+                        }
+                        "aesgcm_enc" | "chacha20poly1305_enc" => {
+                            let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
+                            let mut prefix = [0u8; 7];
+                            rand::rngs::OsRng.fill_bytes(&mut prefix);
+                            out_steps.push(StepOut {
+                                op: op.to_string(),
+                                nonce_b64: None,
+                                tag_b64: None,
+                                aad_b64: Some(general_purpose::STANDARD.encode(&aad)),
+                                stream: Some(StreamInfo {
+                                    nonce_prefix_b64: general_purpose::STANDARD.encode(prefix),
+                                    // each earlier stage wraps a chunk in a 4-byte length and a 16-byte tag
+                                    chunk_size: (STREAM_CHUNK_SIZE + enc_stages.len() * (4 + 16)) as u32,
+                                }),
+                            });
+                            let key_arr: &[u8; 32] = step_key;
+                            if op == "aesgcm_enc" {
+                                let cipher = Aes256Gcm::new(GenericArray::from_slice(key_arr));
+                                enc_stages.push(EncStage {
+                                    prefix,
+                                    encrypt_chunk: Box::new(move |chunk, nonce| {
+                                        cipher.encrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: chunk, aad: &aad })
+                                            .expect("AES-GCM stream enc")
+                                    }),
+                                });
+                            } else {
+                                let cipher = ChaCha20Poly1305::new(Key::from_slice(key_arr));
+                                enc_stages.push(EncStage {
+                                    prefix,
+                                    encrypt_chunk: Box::new(move |chunk, nonce| {
+                                        cipher.encrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: chunk, aad: &aad })
+                                            .expect("chacha stream enc")
+                                    }),
+                                });
+                            }
+                        }
+                        _ => unreachable!("stream_only guarantees only rolling_key/aesgcm_enc/chacha20poly1305_enc"),
                     }
-                    _ => {
-                        panic!("enc: unsupported op {}", op);
+                }
+
+                let header = BundleHeader {
+                    id: format!("{:016x}", rand::random::<u64>()),
+                    kdf,
+                    ephemeral_pub_b64,
+                    pipeline: steps,
+                    steps: out_steps,
+                };
+                let mut reader = BufReader::new(File::open(infile)?);
+                let mut writer = BufWriter::new(File::create(outfile)?);
+                write_bundle_header(&mut writer, &header)?;
+                stream_pipeline_encrypt(&mut reader, &mut writer, &mut enc_stages)?;
+                writer.flush()?;
+            } else {
+                let mut data = fs::read(infile)?;
+                for (s, step_key) in steps.iter().zip(step_keys.iter()) {
+                    let op = s.get("op").and_then(|v| v.as_str()).unwrap();
+                    match op {
+                        "rolling_key" => {
+                            out_steps.push(StepOut { op: op.to_string(), nonce_b64: None, tag_b64: None, aad_b64: None, stream: None });
+                        }
+                        "chacha20poly1305_enc" => {
+                            let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
+                            let mut prefix = [0u8; 7];
+                            rand::rngs::OsRng.fill_bytes(&mut prefix);
+                            data = chacha_stream_encrypt(step_key, &data, &aad, prefix);
+                            out_steps.push(StepOut {
+                                op: op.to_string(),
+                                nonce_b64: None,
+                                tag_b64: None,
+                                aad_b64: Some(general_purpose::STANDARD.encode(&aad)),
+                                stream: Some(StreamInfo {
+                                    nonce_prefix_b64: general_purpose::STANDARD.encode(prefix),
+                                    chunk_size: STREAM_CHUNK_SIZE as u32,
+                                }),
+                            });
+                        }
+                        "aesgcm_enc" => {
+                            let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
+                            let mut prefix = [0u8; 7];
+                            rand::rngs::OsRng.fill_bytes(&mut prefix);
+                            data = aesgcm_stream_encrypt(step_key, &data, &aad, prefix);
+                            out_steps.push(StepOut {
+                                op: op.to_string(),
+                                nonce_b64: None,
+                                tag_b64: None,
+                                aad_b64: Some(general_purpose::STANDARD.encode(&aad)),
+                                stream: Some(StreamInfo {
+                                    nonce_prefix_b64: general_purpose::STANDARD.encode(prefix),
+                                    chunk_size: STREAM_CHUNK_SIZE as u32,
+                                }),
+                            });
+                        }
+                        "aesgcmsiv_enc" => {
+                            let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
+                            let (ct, nonce) = aesgcmsiv_encrypt(step_key, &data, &aad);
+                            data = ct;
+                            out_steps.push(StepOut {
+                                op: op.to_string(),
+                                nonce_b64: Some(general_purpose::STANDARD.encode(nonce)),
+                                tag_b64: None,
+                                aad_b64: Some(general_purpose::STANDARD.encode(&aad)),
+                                stream: None,
+                            });
+                        }
+                        "xchacha20poly1305_enc" => {
+                            let aad = s.get("aad").and_then(|v| v.as_str()).unwrap_or("").as_bytes().to_vec();
+                            let (ct, nonce) = xchacha_encrypt(step_key, &data, &aad);
+                            data = ct;
+                            out_steps.push(StepOut {
+                                op: op.to_string(),
+                                nonce_b64: Some(general_purpose::STANDARD.encode(nonce)),
+                                tag_b64: None,
+                                aad_b64: Some(general_purpose::STANDARD.encode(&aad)),
+                                stream: None,
+                            });
+                        }
+                        _ => {
+                            panic!("enc: unsupported op {}", op);
+                        }
                     }
                 }
-            }
 
-            let bundle = Bundle {
-                id: format!("{:016x}", rand::random::<u64>()),
-                steps: out_steps,
-                data_b64: general_purpose::STANDARD.encode(&data),
-            };
-            fs::write(outfile, serde_json::to_vec_pretty(&bundle)?)?;
+                let header = BundleHeader {
+                    id: format!("{:016x}", rand::random::<u64>()),
+                    kdf,
+                    ephemeral_pub_b64,
+                    pipeline: steps,
+                    steps: out_steps,
+                };
+                let mut writer = BufWriter::new(File::create(outfile)?);
+                write_bundle_header(&mut writer, &header)?;
+                writer.write_all(&data)?;
+                writer.flush()?;
+            }
         }
-        Cmd::Dec { seed, infile, outfile } => {
-            let seedb = parse_seed(&seed);
-            let mut key = {
-                let mut k = [0u8;32];
-                if seedb.len() >= 32 { k.copy_from_slice(&seedb[..32]); }
-                else {
-                    k[..seedb.len()].copy_from_slice(&seedb);
-                    for i in seedb.len()..32 { k[i] = 0; }
+        Cmd::Dec { seed, identity, infile, outfile } => {
+            let mut reader = BufReader::new(File::open(infile)?);
+            let bundle = read_bundle_header(&mut reader)?;
+            let mut key = match (seed, identity) {
+                (Some(seed), None) => derive_key_dec(&seed, &bundle.kdf)?,
+                (None, Some(identity)) => {
+                    let eph_pub_b64 = bundle.ephemeral_pub_b64.as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("bundle has no ephemeral pubkey; it was not encrypted in --recipient mode"))?;
+                    identity_derive_key(&identity, eph_pub_b64)?
                 }
-                k
+                _ => anyhow::bail!("dec: specify exactly one of --key or --identity"),
             };
+
+            anyhow::ensure!(bundle.pipeline.len() == bundle.steps.len(), "dec: bundle pipeline/steps length mismatch");
+
+            // The key schedule only ever moves forward (each rolling_key ratchets
+            // off the previous key), so first replay it forward to recover the
+            // key that was in effect at every pipeline entry...
             let mut counter: u64 = 1;
+            let mut step_keys: Vec<Zeroizing<[u8; 32]>> = Vec::with_capacity(bundle.pipeline.len());
+            for p in bundle.pipeline.iter() {
+                let op = p.get("op").and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("pipeline entry missing string \"op\" field: {}", p))?;
+                if op == "rolling_key" {
+                    let extra = counter.to_be_bytes();
+                    key = rolling_key(&key[..], counter, &extra);
+                    counter += 1;
+                }
+                step_keys.push(key.clone());
+            }
 
-            let bundle_bytes = fs::read(infile)?;
-            let bundle: Bundle = serde_json::from_slice(&bundle_bytes)?;
-            let mut data = general_purpose::STANDARD.decode(bundle.data_b64)?;
-
-            // Replay steps in the same order, but invert enc->dec
-            for (i, s) in bundle.steps.iter().enumerate() {
-                match s.op.as_str() {
-                    "rolling_key" => {
-                        let extra = counter.to_be_bytes();
-                        key = rolling_key(&key, counter, &extra);
-                        counter += 1;
-                    }
-                    "chacha20poly1305_enc" => {
-                        let nonce = general_purpose::STANDARD.decode(s.nonce_b64.as_ref().unwrap()).unwrap();
-                        let mut n12 = [0u8;12]; n12.copy_from_slice(&nonce);
-                        let aad = s.aad_b64.as_ref().map(|x| general_purpose::STANDARD.decode(x).unwrap()).unwrap_or_default();
-                        data = chacha_decrypt(&key, &n12, &data, &aad);
+            // Pipelines built entirely from STREAM-chunked ops mirror the fast
+            // path `Enc` took: the raw data that follows the header is streamed
+            // straight to outfile, chunk by chunk, instead of being buffered
+            // whole in memory.
+            let stream_only = bundle.pipeline.iter().all(|p| {
+                matches!(
+                    p.get("op").and_then(|v| v.as_str()),
+                    Some("rolling_key") | Some("aesgcm_enc") | Some("chacha20poly1305_enc")
+                )
+            });
+
+            if stream_only {
+                let mut dec_stages: Vec<DecStage> = Vec::new();
+                for ((p, s), step_key) in bundle.pipeline.iter().zip(bundle.steps.iter()).zip(step_keys.iter()) {
+                    let op = p.get("op").and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow::anyhow!("pipeline entry missing string \"op\" field: {}", p))?;
+                    if op == "rolling_key" { continue; }
+                    let info = s.stream.as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("{} step missing stream info", op))?;
+                    let prefix_v = general_purpose::STANDARD.decode(&info.nonce_prefix_b64)
+                        .map_err(|e| anyhow::anyhow!("bad nonce prefix: {}", e))?;
+                    anyhow::ensure!(prefix_v.len() == 7, "nonce prefix must be 7 bytes");
+                    let mut prefix = [0u8; 7]; prefix.copy_from_slice(&prefix_v);
+                    let aad = s.aad_b64.as_ref()
+                        .map(|x| general_purpose::STANDARD.decode(x).map_err(|e| anyhow::anyhow!("bad aad: {}", e)))
+                        .transpose()?
+                        .unwrap_or_default();
+                    let key_arr: &[u8; 32] = step_key;
+                    if op == "aesgcm_enc" {
+                        let cipher = Aes256Gcm::new(GenericArray::from_slice(key_arr));
+                        dec_stages.push(DecStage {
+                            prefix,
+                            decrypt_chunk: Box::new(move |ct, nonce| {
+                                cipher.decrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: ct, aad: &aad })
+                                    .map_err(|e| anyhow::anyhow!("AES-GCM stream dec failed: {}", e))
+                            }),
+                        });
+                    } else {
+                        let cipher = ChaCha20Poly1305::new(Key::from_slice(key_arr));
+                        dec_stages.push(DecStage {
+                            prefix,
+                            decrypt_chunk: Box::new(move |ct, nonce| {
+                                cipher.decrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: ct, aad: &aad })
+                                    .map_err(|e| anyhow::anyhow!("chacha stream dec failed: {}", e))
+                            }),
+                        });
                     }
-                    "aesgcm_enc" => {
-                        let nonce = general_purpose::STANDARD.decode(s.nonce_b64.as_ref().unwrap()).unwrap();
-                        let mut n12 = [0u8;12]; n12.copy_from_slice(&nonce);
-                        let aad = s.aad_b64.as_ref().map(|x| general_purpose::STANDARD.decode(x).unwrap()).unwrap_or_default();
-                        data = aesgcm_decrypt(&key, &n12, &data, &aad);
+                }
+
+                let mut writer = BufWriter::new(File::create(outfile)?);
+                stream_pipeline_decrypt(&mut reader, &mut writer, &mut dec_stages)?;
+                writer.flush()?;
+            } else {
+                // ...then undo the data-transforming ops in the reverse of the
+                // order they were applied: enc ran pt -> step1 -> step2 -> ct, so
+                // dec must run ct -> inverse(step2) -> inverse(step1) -> pt.
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                for ((p, s), step_key) in bundle.pipeline.iter().zip(bundle.steps.iter()).zip(step_keys.iter()).rev() {
+                    let op = p.get("op").and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow::anyhow!("pipeline entry missing string \"op\" field: {}", p))?;
+                    let aad = s.aad_b64.as_ref()
+                        .map(|x| general_purpose::STANDARD.decode(x).map_err(|e| anyhow::anyhow!("bad aad: {}", e)))
+                        .transpose()?
+                        .unwrap_or_default();
+                    match op {
+                        "rolling_key" => {}
+                        "chacha20poly1305_enc" => {
+                            let info = s.stream.as_ref()
+                                .ok_or_else(|| anyhow::anyhow!("chacha20poly1305_enc step missing stream info"))?;
+                            let prefix_v = general_purpose::STANDARD.decode(&info.nonce_prefix_b64)
+                                .map_err(|e| anyhow::anyhow!("bad nonce prefix: {}", e))?;
+                            anyhow::ensure!(prefix_v.len() == 7, "nonce prefix must be 7 bytes");
+                            let mut prefix = [0u8; 7]; prefix.copy_from_slice(&prefix_v);
+                            data = chacha_stream_decrypt(step_key, &data, &aad, prefix)?;
+                        }
+                        "aesgcm_enc" => {
+                            let info = s.stream.as_ref()
+                                .ok_or_else(|| anyhow::anyhow!("aesgcm_enc step missing stream info"))?;
+                            let prefix_v = general_purpose::STANDARD.decode(&info.nonce_prefix_b64)
+                                .map_err(|e| anyhow::anyhow!("bad nonce prefix: {}", e))?;
+                            anyhow::ensure!(prefix_v.len() == 7, "nonce prefix must be 7 bytes");
+                            let mut prefix = [0u8; 7]; prefix.copy_from_slice(&prefix_v);
+                            data = aesgcm_stream_decrypt(step_key, &data, &aad, prefix)?;
+                        }
+                        "aesgcmsiv_enc" => {
+                            let nonce_b64 = s.nonce_b64.as_ref()
+                                .ok_or_else(|| anyhow::anyhow!("aesgcmsiv_enc step missing nonce"))?;
+                            let nonce = general_purpose::STANDARD.decode(nonce_b64)
+                                .map_err(|e| anyhow::anyhow!("bad nonce: {}", e))?;
+                            anyhow::ensure!(nonce.len() == 12, "aesgcmsiv_enc nonce must be 12 bytes");
+                            let mut n12 = [0u8;12]; n12.copy_from_slice(&nonce);
+                            data = aesgcmsiv_decrypt(step_key, &n12, &data, &aad)?;
+                        }
+                        "xchacha20poly1305_enc" => {
+                            let nonce_b64 = s.nonce_b64.as_ref()
+                                .ok_or_else(|| anyhow::anyhow!("xchacha20poly1305_enc step missing nonce"))?;
+                            let nonce = general_purpose::STANDARD.decode(nonce_b64)
+                                .map_err(|e| anyhow::anyhow!("bad nonce: {}", e))?;
+                            anyhow::ensure!(nonce.len() == 24, "xchacha20poly1305_enc nonce must be 24 bytes");
+                            let mut n24 = [0u8;24]; n24.copy_from_slice(&nonce);
+                            data = xchacha_decrypt(step_key, &n24, &data, &aad)?;
+                        }
+                        other => anyhow::bail!("dec: unsupported pipeline op {}", other),
                     }
-                    other => panic!("dec: unsupported step {}", other),
                 }
-            }
 
-            fs::write(outfile, data)?;
+                fs::write(outfile, data)?;
+            }
         }
     }
 
@@ -243,3 +910,181 @@ fn main() -> Result<()> {
 
 // The generator injects the pipeline steps here as JSON:
 const PIPELINE_JSON: &str = r#"[{"op":"rolling_key","extra":"time_ns"},{"op":"aesgcm_enc","aad":"adap"},{"op":"chacha20poly1305_enc","aad":"evolve"}]"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key(b: u8) -> [u8; 32] {
+        [b; 32]
+    }
+
+    #[test]
+    fn aesgcm_stream_roundtrip() {
+        let key = test_key(1);
+        let mut prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut prefix);
+        let pt = vec![7u8; STREAM_CHUNK_SIZE * 2 + 123]; // spans multiple chunks
+        let ct = aesgcm_stream_encrypt(&key, &pt, b"aad", prefix);
+        let out = aesgcm_stream_decrypt(&key, &ct, b"aad", prefix).expect("decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+
+    #[test]
+    fn chacha_stream_roundtrip() {
+        let key = test_key(2);
+        let mut prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut prefix);
+        let pt = vec![9u8; STREAM_CHUNK_SIZE + 1];
+        let ct = chacha_stream_encrypt(&key, &pt, b"aad", prefix);
+        let out = chacha_stream_decrypt(&key, &ct, b"aad", prefix).expect("decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+
+    #[test]
+    fn aesgcmsiv_roundtrip() {
+        let key = test_key(3);
+        let pt = b"nonce-misuse resistant message".to_vec();
+        let (ct, nonce) = aesgcmsiv_encrypt(&key, &pt, b"aad");
+        let out = aesgcmsiv_decrypt(&key, &nonce, &ct, b"aad").expect("decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+
+    #[test]
+    fn aesgcmsiv_decrypt_rejects_wrong_key() {
+        let key = test_key(3);
+        let wrong_key = test_key(30);
+        let pt = b"nonce-misuse resistant message".to_vec();
+        let (ct, nonce) = aesgcmsiv_encrypt(&key, &pt, b"aad");
+        assert!(aesgcmsiv_decrypt(&wrong_key, &nonce, &ct, b"aad").is_err());
+    }
+
+    #[test]
+    fn xchacha_roundtrip() {
+        let key = test_key(4);
+        let pt = b"wide nonce message".to_vec();
+        let (ct, nonce) = xchacha_encrypt(&key, &pt, b"aad");
+        let out = xchacha_decrypt(&key, &nonce, &ct, b"aad").expect("decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+
+    #[test]
+    fn xchacha_decrypt_rejects_wrong_key() {
+        let key = test_key(4);
+        let wrong_key = test_key(40);
+        let pt = b"wide nonce message".to_vec();
+        let (ct, nonce) = xchacha_encrypt(&key, &pt, b"aad");
+        assert!(xchacha_decrypt(&wrong_key, &nonce, &ct, b"aad").is_err());
+    }
+
+    #[test]
+    fn frame_decrypt_stream_rejects_truncated_input() {
+        let key = test_key(5);
+        let prefix = [0u8; 7];
+        let ct = aesgcm_stream_encrypt(&key, b"some plaintext", b"", prefix);
+        let truncated = &ct[..ct.len() - 1];
+        assert!(aesgcm_stream_decrypt(&key, truncated, b"", prefix).is_err());
+    }
+
+    // A dropped *whole* trailing chunk leaves the length-prefixed framing
+    // itself self-consistent (commit 5c7137f only caught truncation that
+    // lands inside a frame), so this must fail via the AEAD tag mismatch on
+    // the now-mislabeled last chunk instead of panicking.
+    #[test]
+    fn frame_decrypt_stream_rejects_dropped_trailing_chunk() {
+        let key = test_key(50);
+        let prefix = [0u8; 7];
+        let pt = vec![3u8; STREAM_CHUNK_SIZE + 100]; // two chunks
+        let ct = aesgcm_stream_encrypt(&key, &pt, b"", prefix);
+        let first_chunk_len = u32::from_be_bytes(ct[..4].try_into().unwrap()) as usize;
+        let dropped = &ct[..4 + first_chunk_len];
+        assert!(aesgcm_stream_decrypt(&key, dropped, b"", prefix).is_err());
+    }
+
+    // Runs the default pipeline's ops by hand through the buffered-path
+    // helpers (not through Enc/Dec): enc runs pt -> aesgcm -> chacha, and
+    // undoing chacha first, then aesgcm, recovers the plaintext.
+    #[test]
+    fn default_pipeline_roundtrip() {
+        let root_key = test_key(6);
+        let pt = b"multi-op pipeline round trip".to_vec();
+
+        // Encrypt: rolling_key -> aesgcm_enc -> chacha20poly1305_enc
+        let k1 = rolling_key(&root_key, 1, &1u64.to_be_bytes());
+        let mut aesgcm_prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut aesgcm_prefix);
+        let after_aesgcm = aesgcm_stream_encrypt(&k1, &pt, b"adap", aesgcm_prefix);
+        let mut chacha_prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut chacha_prefix);
+        let ct = chacha_stream_encrypt(&k1, &after_aesgcm, b"evolve", chacha_prefix);
+
+        // Decrypt: key schedule replays forward (there's only one rolling_key
+        // step here), but the data transforms invert in reverse order.
+        let k1_dec = rolling_key(&root_key, 1, &1u64.to_be_bytes());
+        let after_chacha = chacha_stream_decrypt(&k1_dec, &ct, b"evolve", chacha_prefix)
+            .expect("chacha decrypt should succeed");
+        let out = aesgcm_stream_decrypt(&k1_dec, &after_chacha, b"adap", aesgcm_prefix)
+            .expect("aesgcm decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+
+    // The fused multi-stage streaming path (`Enc`'s stream_only fast path) is
+    // what actually makes chunk0-3's "no whole-file-in-memory" goal real: a
+    // `Read` is turned into a `Write` one STREAM_CHUNK_SIZE piece at a time
+    // through every stage, never materializing the whole plaintext or
+    // ciphertext as a single buffer.
+    #[test]
+    fn stream_pipeline_roundtrip_multi_stage() {
+        let k1 = test_key(7);
+        let k2 = test_key(8);
+        let pt = vec![11u8; STREAM_CHUNK_SIZE * 2 + 77]; // spans multiple chunks
+
+        let mut aesgcm_prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut aesgcm_prefix);
+        let mut chacha_prefix = [0u8; 7];
+        rand::rngs::OsRng.fill_bytes(&mut chacha_prefix);
+
+        let aesgcm_cipher = Aes256Gcm::new(GenericArray::from_slice(&k1));
+        let mut enc_stages = vec![
+            EncStage {
+                prefix: aesgcm_prefix,
+                encrypt_chunk: Box::new(move |chunk, nonce| {
+                    aesgcm_cipher.encrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: chunk, aad: b"adap" })
+                        .expect("AES-GCM stream enc")
+                }),
+            },
+            EncStage {
+                prefix: chacha_prefix,
+                encrypt_chunk: Box::new(move |chunk, nonce| {
+                    let cipher = ChaCha20Poly1305::new(Key::from_slice(&k2));
+                    cipher.encrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: chunk, aad: b"evolve" })
+                        .expect("chacha stream enc")
+                }),
+            },
+        ];
+        let mut ct = Vec::new();
+        stream_pipeline_encrypt(pt.as_slice(), &mut ct, &mut enc_stages).expect("stream encrypt should succeed");
+
+        let aesgcm_cipher = Aes256Gcm::new(GenericArray::from_slice(&k1));
+        let mut dec_stages = vec![
+            DecStage {
+                prefix: aesgcm_prefix,
+                decrypt_chunk: Box::new(move |ct, nonce| {
+                    aesgcm_cipher.decrypt(GenericArray::from_slice(&nonce), aes_gcm::aead::Payload { msg: ct, aad: b"adap" })
+                        .map_err(|e| anyhow::anyhow!("AES-GCM stream dec failed: {}", e))
+                }),
+            },
+            DecStage {
+                prefix: chacha_prefix,
+                decrypt_chunk: Box::new(move |ct, nonce| {
+                    let cipher = ChaCha20Poly1305::new(Key::from_slice(&k2));
+                    cipher.decrypt(&nonce.into(), chacha20poly1305::aead::Payload { msg: ct, aad: b"evolve" })
+                        .map_err(|e| anyhow::anyhow!("chacha stream dec failed: {}", e))
+                }),
+            },
+        ];
+        let mut out = Vec::new();
+        stream_pipeline_decrypt(ct.as_slice(), &mut out, &mut dec_stages).expect("stream decrypt should succeed");
+        assert_eq!(out, pt);
+    }
+}